@@ -1,7 +1,20 @@
-//! WASM SIMD-accelerated Bao operations using the official BLAKE3 crate.
+//! WASM-exposed Bao operations built on the official BLAKE3 crate.
 //!
-//! This module provides high-performance chunk CV and parent CV computations
-//! for Bao verified streaming, using the blake3 crate's optimized SIMD implementations.
+//! Chunk/parent chaining-value primitives, single-pass tree building, and Bao combined
+//! encoding/slice verification are implemented directly against `blake3::guts` - the
+//! crate's small, deprecated shim around its internal chunk/parent compression
+//! (`ChunkState::new(chunk_counter)`, `parent_cv(left, right, is_root)`). That shim only
+//! ever supports plain (unkeyed) hashing, so all of the tree/Bao functions below are
+//! plain-hash only.
+//!
+//! Keyed and derive_key hashing (the `MODE_*` constants) are a separate, whole-buffer
+//! feature built on the public `blake3::Hasher` API (`new_keyed`/`new_derive_key`) via
+//! `hash_buffer`/`set_xof_input`, since there's no low-level guts primitive for a keyed
+//! chunk or parent CV to build a keyed tree from.
+//!
+//! Every operation is scoped to a `Ctx` session (see `create_context`) rather than
+//! a single global buffer pair, so independent callers - e.g. one per Web Worker -
+//! can hash concurrently without aliasing each other's state.
 
 use wasm_bindgen::prelude::*;
 
@@ -13,19 +26,53 @@ const OUT_LEN: usize = 32;
 const INPUT_SIZE: usize = 1048576;  // 1MB
 const OUTPUT_SIZE: usize = 1048576; // 1MB
 
-static mut INPUT_BUFFER: [u8; INPUT_SIZE] = [0u8; INPUT_SIZE];
-static mut OUTPUT_BUFFER: [u8; OUTPUT_SIZE] = [0u8; OUTPUT_SIZE];
+/// Mode constants accepted by `hash_buffer`/`set_xof_input`.
+const MODE_PLAIN: u32 = 0;
+const MODE_KEYED_HASH: u32 = 1;
+const MODE_DERIVE_KEY_MATERIAL: u32 = 2;
+
+/// A single hashing session: its own input/output buffers plus whatever keyed-mode
+/// and XOF state a `hash_buffer`/`finalize_xof` call needs to carry between calls.
+/// Boxed and handed to JS as a raw pointer by `create_context`, so each Web Worker
+/// (or any other concurrent caller) can own an independent session instead of
+/// contending for one pair of global buffers.
+struct Ctx {
+    input: Box<[u8; INPUT_SIZE]>,
+    output: Box<[u8; OUTPUT_SIZE]>,
+    key: [u8; 32],
+    derive_context: Option<String>,
+    xof_hasher: Option<blake3::Hasher>,
+}
+
+impl Ctx {
+    fn new() -> Self {
+        Ctx {
+            input: Box::new([0u8; INPUT_SIZE]),
+            output: Box::new([0u8; OUTPUT_SIZE]),
+            key: [0u8; 32],
+            derive_context: None,
+            xof_hasher: None,
+        }
+    }
+}
+
+/// Create a new hashing session and return an opaque handle to it.
+/// The caller owns the handle and must pass it to `destroy_context` when done.
+#[wasm_bindgen]
+pub fn create_context() -> *mut Ctx {
+    Box::into_raw(Box::new(Ctx::new()))
+}
 
-/// Get pointer to input buffer for direct memory access from JS
+/// Get pointer to a session's input buffer for direct memory access from JS
 #[wasm_bindgen]
-pub fn get_input_ptr() -> *mut u8 {
-    unsafe { INPUT_BUFFER.as_mut_ptr() }
+pub fn ctx_input_ptr(ctx: *mut Ctx) -> *mut u8 {
+    unsafe { (*ctx).input.as_mut_ptr() }
 }
 
-/// Get pointer to output buffer for direct memory access from JS
+/// Get pointer to a session's output buffer for direct memory access from JS
 #[wasm_bindgen]
-pub fn get_output_ptr() -> *const u8 {
-    unsafe { OUTPUT_BUFFER.as_ptr() }
+pub fn ctx_output_ptr(ctx: *mut Ctx) -> *const u8 {
+    unsafe { (*ctx).output.as_ptr() }
 }
 
 /// Get input buffer size
@@ -40,6 +87,14 @@ pub fn get_output_size() -> usize {
     OUTPUT_SIZE
 }
 
+/// Destroy a session created by `create_context`, freeing its buffers.
+#[wasm_bindgen]
+pub fn destroy_context(ctx: *mut Ctx) {
+    unsafe {
+        drop(Box::from_raw(ctx));
+    }
+}
+
 /// Get SIMD status info
 #[wasm_bindgen]
 pub fn get_simd_info() -> String {
@@ -49,9 +104,10 @@ pub fn get_simd_info() -> String {
     { "SIMD NOT enabled".to_string() }
 }
 
-/// Compute chunk chaining value using blake3 crate's guts module
+/// Compute a chunk's chaining value via `blake3::guts::ChunkState`, the crate's small,
+/// deprecated shim around its internal chunk compression. `guts` only supports plain
+/// (unkeyed) hashing - there's no key or flags parameter to pass here.
 fn compute_chunk_cv(data: &[u8], chunk_index: u64, is_root: bool) -> [u8; 32] {
-    // Use the guts module with deprecated API (still works)
     #[allow(deprecated)]
     {
         let mut state = blake3::guts::ChunkState::new(chunk_index);
@@ -61,7 +117,8 @@ fn compute_chunk_cv(data: &[u8], chunk_index: u64, is_root: bool) -> [u8; 32] {
     }
 }
 
-/// Compute parent chaining value from two child CVs using blake3 crate
+/// Compute a parent chaining value from two child CVs via `blake3::guts::parent_cv`,
+/// plain (unkeyed) hashing only, same as `compute_chunk_cv`.
 fn compute_parent_cv(left: &[u8; 32], right: &[u8; 32], is_root: bool) -> [u8; 32] {
     #[allow(deprecated)]
     {
@@ -73,90 +130,209 @@ fn compute_parent_cv(left: &[u8; 32], right: &[u8; 32], is_root: bool) -> [u8; 3
 }
 
 /// Compute chunk CV - main export
-/// Reads chunk data from INPUT_BUFFER, writes CV to OUTPUT_BUFFER
+/// Reads chunk data from the session's input buffer, writes CV to its output buffer.
+/// Plain (unkeyed) hashing only - see the module doc for why keyed/derive_key hashing
+/// is a separate, whole-buffer feature (`hash_buffer`) instead of living here.
 #[wasm_bindgen]
-pub fn chunk_cv(chunk_len: usize, chunk_index: u64, is_root: bool) {
+pub fn chunk_cv(ctx: *mut Ctx, chunk_len: usize, chunk_index: u64, is_root: bool) {
     unsafe {
-        let data = &INPUT_BUFFER[..chunk_len];
+        let ctx = &mut *ctx;
+        let data = &ctx.input[..chunk_len];
         let cv = compute_chunk_cv(data, chunk_index, is_root);
-        OUTPUT_BUFFER[..OUT_LEN].copy_from_slice(&cv);
+        ctx.output[..OUT_LEN].copy_from_slice(&cv);
     }
 }
 
 /// Compute parent CV from two child CVs
-/// Reads left CV from INPUT_BUFFER[0..32], right from INPUT_BUFFER[32..64]
-/// Writes result to OUTPUT_BUFFER[0..32]
+/// Reads left CV from input[0..32], right from input[32..64]
+/// Writes result to output[0..32]. Plain (unkeyed) hashing only, see `chunk_cv`.
 #[wasm_bindgen]
-pub fn parent_cv(is_root: bool) {
+pub fn parent_cv(ctx: *mut Ctx, is_root: bool) {
     unsafe {
-        let left: [u8; 32] = INPUT_BUFFER[..32].try_into().unwrap();
-        let right: [u8; 32] = INPUT_BUFFER[32..64].try_into().unwrap();
+        let ctx = &mut *ctx;
+        let left: [u8; 32] = ctx.input[..32].try_into().unwrap();
+        let right: [u8; 32] = ctx.input[32..64].try_into().unwrap();
         let cv = compute_parent_cv(&left, &right, is_root);
-        OUTPUT_BUFFER[..OUT_LEN].copy_from_slice(&cv);
+        ctx.output[..OUT_LEN].copy_from_slice(&cv);
+    }
+}
+
+/// Load a 32-byte key from input[0..32] for subsequent `MODE_KEYED_HASH` calls to
+/// `hash_buffer`/`set_xof_input` on this session.
+#[wasm_bindgen]
+pub fn set_key(ctx: *mut Ctx) {
+    unsafe {
+        let ctx = &mut *ctx;
+        ctx.key = ctx.input[..32].try_into().unwrap();
+    }
+}
+
+/// Record `[context_ptr, context_ptr + context_len)` as the session's derive_key context
+/// string for subsequent `MODE_DERIVE_KEY_MATERIAL` calls to `hash_buffer`/`set_xof_input`.
+/// BLAKE3's derive_key context hashing happens inside `blake3::Hasher::new_derive_key`
+/// itself, so there's nothing to precompute here beyond validating the context is UTF-8
+/// (BLAKE3 contexts are `&str`, not arbitrary bytes) and stashing it for later use.
+/// Returns 1 on success, 0 if the context isn't valid UTF-8.
+#[wasm_bindgen]
+pub fn derive_key(ctx: *mut Ctx, context_ptr: *const u8, context_len: usize) -> u32 {
+    unsafe {
+        let ctx = &mut *ctx;
+        let context_bytes = core::slice::from_raw_parts(context_ptr, context_len);
+        match core::str::from_utf8(context_bytes) {
+            Ok(context) => {
+                ctx.derive_context = Some(context.to_string());
+                1
+            }
+            Err(_) => 0,
+        }
+    }
+}
+
+/// Build a fresh `blake3::Hasher` for `mode`. Returns `None` for `MODE_DERIVE_KEY_MATERIAL`
+/// if no context has been recorded yet via `derive_key`.
+fn mode_hasher(ctx: &Ctx, mode: u32) -> Option<blake3::Hasher> {
+    match mode {
+        MODE_KEYED_HASH => Some(blake3::Hasher::new_keyed(&ctx.key)),
+        MODE_DERIVE_KEY_MATERIAL => ctx.derive_context.as_deref().map(blake3::Hasher::new_derive_key),
+        _ => Some(blake3::Hasher::new()),
+    }
+}
+
+/// Hash `data_len` bytes from the session's input buffer under `mode` using
+/// `blake3::Hasher` (plain/keyed/derive_key), writing the 32-byte digest to the output
+/// buffer. Returns 32 on success, or 0 if `data_len` exceeds the input buffer or
+/// `MODE_DERIVE_KEY_MATERIAL` is requested before a context has been set via `derive_key`.
+/// `mode` is one of `MODE_PLAIN`, `MODE_KEYED_HASH`, `MODE_DERIVE_KEY_MATERIAL`.
+#[wasm_bindgen]
+pub fn hash_buffer(ctx: *mut Ctx, data_len: usize, mode: u32) -> usize {
+    unsafe {
+        let ctx = &mut *ctx;
+        if data_len > ctx.input.len() {
+            return 0;
+        }
+        let Some(mut hasher) = mode_hasher(ctx, mode) else {
+            return 0;
+        };
+        hasher.update(&ctx.input[..data_len]);
+        let cv = hasher.finalize();
+        ctx.output[..OUT_LEN].copy_from_slice(cv.as_bytes());
+        OUT_LEN
+    }
+}
+
+/// Hash `data_len` bytes from the session's input buffer under `mode` and store the
+/// resulting `blake3::Hasher` as the session's XOF source for subsequent `finalize_xof`
+/// calls. There's no low-level guts primitive for extended (XOF) output - only
+/// `Hasher::finalize_xof` exposes it - so, like `hash_buffer`, this works over the whole
+/// buffer rather than a single chunk or parent node.
+/// Returns 1 on success, or 0 if `data_len` exceeds the input buffer or
+/// `MODE_DERIVE_KEY_MATERIAL` is requested before a context has been set via `derive_key`.
+/// `mode` is one of `MODE_PLAIN`, `MODE_KEYED_HASH`, `MODE_DERIVE_KEY_MATERIAL`.
+#[wasm_bindgen]
+pub fn set_xof_input(ctx: *mut Ctx, data_len: usize, mode: u32) -> u32 {
+    unsafe {
+        let ctx = &mut *ctx;
+        if data_len > ctx.input.len() {
+            return 0;
+        }
+        let Some(mut hasher) = mode_hasher(ctx, mode) else {
+            return 0;
+        };
+        hasher.update(&ctx.input[..data_len]);
+        ctx.xof_hasher = Some(hasher);
+        1
+    }
+}
+
+/// Produce `out_len` bytes of BLAKE3 extended (XOF) output from the session's input, most
+/// recently recorded by `set_xof_input`, starting at output-stream byte offset `seek`.
+/// Writes to the session's output buffer and returns the number of bytes written. Returns 0
+/// if no XOF input has been set yet.
+///
+/// For `out_len` larger than OUTPUT_SIZE, call again with `seek` advanced by the previous
+/// call's return value.
+#[wasm_bindgen]
+pub fn finalize_xof(ctx: *mut Ctx, out_len: usize, seek: u64) -> usize {
+    unsafe {
+        let ctx = &mut *ctx;
+        let Some(hasher) = ctx.xof_hasher.as_ref() else {
+            return 0;
+        };
+        let out_len = out_len.min(OUTPUT_SIZE);
+        let mut reader = hasher.finalize_xof();
+        reader.set_position(seek);
+        reader.fill(&mut ctx.output[..out_len]);
+        out_len
     }
 }
 
 /// Batch compute chunk CVs
-/// Reads num_chunks * 1024 bytes from INPUT_BUFFER
-/// Writes num_chunks * 32 bytes to OUTPUT_BUFFER
+/// Reads num_chunks * 1024 bytes from the session's input buffer
+/// Writes num_chunks * 32 bytes to its output buffer
+///
+/// `guts` exposes no SIMD batch-hashing entry point - it's a small, deprecated
+/// single-chunk-at-a-time shim - so this is a sequential loop over `compute_chunk_cv`,
+/// one `ChunkState` per chunk, rather than a true parallel batch. Plain (unkeyed)
+/// hashing only, see the module doc.
 #[wasm_bindgen]
-pub fn batch_chunk_cvs(num_chunks: usize, start_index: u64) {
+pub fn batch_chunk_cvs(ctx: *mut Ctx, num_chunks: usize, start_index: u64) {
     unsafe {
+        let ctx = &mut *ctx;
         for i in 0..num_chunks {
             let offset = i * CHUNK_LEN;
-            let chunk_end = offset + CHUNK_LEN;
-            let data = &INPUT_BUFFER[offset..chunk_end];
+            let data = &ctx.input[offset..offset + CHUNK_LEN];
             let cv = compute_chunk_cv(data, start_index + i as u64, false);
-
             let out_offset = i * OUT_LEN;
-            OUTPUT_BUFFER[out_offset..out_offset + OUT_LEN].copy_from_slice(&cv);
+            ctx.output[out_offset..out_offset + OUT_LEN].copy_from_slice(&cv);
         }
     }
 }
 
 /// Batch compute parent CVs
-/// Reads num_pairs * 64 bytes (CV pairs) from INPUT_BUFFER
-/// Writes num_pairs * 32 bytes to OUTPUT_BUFFER
-/// root_index: if >= 0, marks that pair as root
+/// Reads num_pairs * 64 bytes (CV pairs) from the session's input buffer
+/// Writes num_pairs * 32 bytes to its output buffer
+/// root_index: if >= 0, marks that pair as root. Plain (unkeyed) hashing only.
 #[wasm_bindgen]
-pub fn batch_parent_cvs(num_pairs: usize, root_index: i32) {
+pub fn batch_parent_cvs(ctx: *mut Ctx, num_pairs: usize, root_index: i32) {
     unsafe {
+        let ctx = &mut *ctx;
         for i in 0..num_pairs {
             let in_offset = i * 64;
-            let left: [u8; 32] = INPUT_BUFFER[in_offset..in_offset + 32].try_into().unwrap();
-            let right: [u8; 32] = INPUT_BUFFER[in_offset + 32..in_offset + 64].try_into().unwrap();
+            let left: [u8; 32] = ctx.input[in_offset..in_offset + 32].try_into().unwrap();
+            let right: [u8; 32] = ctx.input[in_offset + 32..in_offset + 64].try_into().unwrap();
 
             let is_root = root_index == i as i32;
             let cv = compute_parent_cv(&left, &right, is_root);
 
             let out_offset = i * OUT_LEN;
-            OUTPUT_BUFFER[out_offset..out_offset + OUT_LEN].copy_from_slice(&cv);
+            ctx.output[out_offset..out_offset + OUT_LEN].copy_from_slice(&cv);
         }
     }
 }
 
 /// Build entire Merkle tree in a single pass
-/// Reads num_leaves * 32 bytes (leaf CVs) from INPUT_BUFFER
-/// Writes 32-byte root CV to OUTPUT_BUFFER
-/// Returns bytes written (32) or 0 on error
+/// Reads num_leaves * 32 bytes (leaf CVs) from the session's input buffer
+/// Writes 32-byte root CV to its output buffer
+/// Returns bytes written (32) or 0 on error. Plain (unkeyed) hashing only.
 #[wasm_bindgen]
-pub fn build_tree_single_pass(num_leaves: usize) -> usize {
+pub fn build_tree_single_pass(ctx: *mut Ctx, num_leaves: usize) -> usize {
     unsafe {
+        let ctx = &mut *ctx;
         if num_leaves == 0 {
             return 0;
         }
         if num_leaves == 1 {
             // Single leaf is root - copy from input to output
-            OUTPUT_BUFFER[..32].copy_from_slice(&INPUT_BUFFER[..32]);
+            ctx.output[..32].copy_from_slice(&ctx.input[..32]);
             return 32;
         }
 
-        // Read all leaf CVs from INPUT_BUFFER
+        // Read all leaf CVs from the input buffer
         let mut current_level: Vec<[u8; 32]> = Vec::with_capacity(num_leaves);
         for i in 0..num_leaves {
             let offset = i * 32;
             let mut cv = [0u8; 32];
-            cv.copy_from_slice(&INPUT_BUFFER[offset..offset + 32]);
+            cv.copy_from_slice(&ctx.input[offset..offset + 32]);
             current_level.push(cv);
         }
 
@@ -182,11 +358,216 @@ pub fn build_tree_single_pass(num_leaves: usize) -> usize {
         }
 
         // Write root to output buffer
-        OUTPUT_BUFFER[..32].copy_from_slice(&current_level[0]);
+        ctx.output[..32].copy_from_slice(&current_level[0]);
         32 // Return bytes written
     }
 }
 
+/// Number of 1024-byte chunks needed to cover `data_len` bytes (empty input is one empty chunk).
+fn chunk_count(data_len: usize) -> usize {
+    if data_len == 0 {
+        1
+    } else {
+        (data_len + CHUNK_LEN - 1) / CHUNK_LEN
+    }
+}
+
+/// Chunk count of the left subtree per the Bao/BLAKE3 tree rule: the largest
+/// power of two strictly less than `total_chunks`.
+fn left_subtree_chunks(total_chunks: usize) -> usize {
+    let mut power = 1;
+    while power * 2 < total_chunks {
+        power *= 2;
+    }
+    power
+}
+
+/// Reduce an arbitrary `data_len`-byte chunk-group span read out of `ctx.input` to its
+/// subtree CV, splitting a ragged (non-power-of-two) span per the standard BLAKE3 tree
+/// rule - left subtree is the largest power-of-two number of chunks strictly less than
+/// the total, the remainder forms the right subtree - and recursing on each side.
+/// There's no guts batch-hashing primitive to reduce a whole level at once (see the
+/// module doc), so this is a single `compute_chunk_cv`/`compute_parent_cv` call per node.
+fn hash_subtree_cv(ctx: &Ctx, data_offset: usize, data_len: usize, start_chunk: u64, is_root: bool) -> [u8; 32] {
+    let num_chunks = chunk_count(data_len);
+    if num_chunks <= 1 {
+        let data = &ctx.input[data_offset..data_offset + data_len];
+        return compute_chunk_cv(data, start_chunk, is_root);
+    }
+
+    let left_chunks = left_subtree_chunks(num_chunks);
+    let left_len = left_chunks * CHUNK_LEN;
+    let right_len = data_len - left_len;
+
+    let left_cv = hash_subtree_cv(ctx, data_offset, left_len, start_chunk, false);
+    let right_cv = hash_subtree_cv(ctx, data_offset + left_len, right_len, start_chunk + left_chunks as u64, false);
+    compute_parent_cv(&left_cv, &right_cv, is_root)
+}
+
+/// Hash a chunk-group span directly to its subtree CV. Reads `data_len` bytes from the
+/// session's input buffer, covering the chunks starting at `start_chunk`. Accepts any
+/// `data_len`, handling a non-power-of-two-aligned right edge per the standard BLAKE3
+/// tree rule. Writes the 32-byte subtree CV to the output buffer and returns the number
+/// of bytes written (32), or 0 if `data_len` exceeds the input buffer. Mark the node
+/// root only when the caller knows this span is the whole tree. Plain (unkeyed) hashing
+/// only, see the module doc.
+#[wasm_bindgen]
+pub fn hash_subtree(ctx: *mut Ctx, data_len: usize, start_chunk: u64, is_root: bool) -> usize {
+    unsafe {
+        let ctx = &mut *ctx;
+        if data_len > ctx.input.len() {
+            return 0;
+        }
+        let cv = hash_subtree_cv(ctx, 0, data_len, start_chunk, is_root);
+        ctx.output[..32].copy_from_slice(&cv);
+        32
+    }
+}
+
+/// Recursively encode the subtree covering `ctx.input[data_offset..data_offset+data_len]`
+/// into `ctx.output` starting at `*out`, in Bao's pre-order (parent node before the
+/// subtree it covers, chunk bytes at the leaves). Returns the subtree's chaining value.
+fn encode_subtree(ctx: &mut Ctx, data_offset: usize, data_len: usize, chunk_index: u64, is_root: bool, out: &mut usize) -> [u8; 32] {
+    let num_chunks = chunk_count(data_len);
+    if num_chunks <= 1 {
+        let data = &ctx.input[data_offset..data_offset + data_len];
+        let cv = compute_chunk_cv(data, chunk_index, is_root);
+        ctx.output[*out..*out + data_len].copy_from_slice(data);
+        *out += data_len;
+        cv
+    } else {
+        let left_chunks = left_subtree_chunks(num_chunks);
+        let left_len = left_chunks * CHUNK_LEN;
+        let right_len = data_len - left_len;
+
+        let parent_offset = *out;
+        *out += 64;
+        let left_cv = encode_subtree(ctx, data_offset, left_len, chunk_index, false, out);
+        let right_cv = encode_subtree(ctx, data_offset + left_len, right_len, chunk_index + left_chunks as u64, false, out);
+        ctx.output[parent_offset..parent_offset + 32].copy_from_slice(&left_cv);
+        ctx.output[parent_offset + 32..parent_offset + 64].copy_from_slice(&right_cv);
+
+        compute_parent_cv(&left_cv, &right_cv, is_root)
+    }
+}
+
+/// Produce the Bao combined encoding of `content_len` bytes of chunk data held in the
+/// session's input buffer: an 8-byte little-endian length header followed by the tree in
+/// pre-order, with each parent node's 64 bytes (left CV || right CV) written
+/// immediately before the subtree it covers.
+/// Writes the encoding to the output buffer and returns the number of bytes written, or 0
+/// if `content_len` exceeds the input buffer or its encoding (header + parent nodes +
+/// content) would not fit in the output buffer. Plain (unkeyed) hashing only, see the
+/// module doc.
+#[wasm_bindgen]
+pub fn bao_encode(ctx: *mut Ctx, content_len: usize) -> usize {
+    unsafe {
+        let ctx = &mut *ctx;
+        if content_len > ctx.input.len() {
+            return 0;
+        }
+        let num_chunks = chunk_count(content_len);
+        let encoded_len = (num_chunks - 1)
+            .checked_mul(64)
+            .and_then(|parent_bytes| parent_bytes.checked_add(content_len))
+            .and_then(|n| n.checked_add(8));
+        match encoded_len {
+            Some(encoded_len) if encoded_len <= ctx.output.len() => {}
+            _ => return 0,
+        }
+
+        ctx.output[..8].copy_from_slice(&(content_len as u64).to_le_bytes());
+        let mut out = 8;
+        encode_subtree(ctx, 0, content_len, 0, true, &mut out);
+        out
+    }
+}
+
+/// Recursively walk a Bao slice encoding read from `ctx.input` starting at `*cursor`,
+/// descending only into subtrees that overlap `[range_start, range_end)` and taking the
+/// sibling CV directly from the parent node otherwise. Recomputes every CV it descends
+/// into and checks it against the parent's recorded value, returning `None` on the first
+/// mismatch. Returns the subtree's chaining value on success.
+fn decode_subtree(
+    ctx: &Ctx,
+    cursor: &mut usize,
+    offset: usize,
+    data_len: usize,
+    chunk_index: u64,
+    is_root: bool,
+    range_start: usize,
+    range_end: usize,
+) -> Option<[u8; 32]> {
+    let num_chunks = chunk_count(data_len);
+    if num_chunks <= 1 {
+        let end = cursor.checked_add(data_len)?;
+        if end > ctx.input.len() {
+            return None;
+        }
+        let data = &ctx.input[*cursor..end];
+        let cv = compute_chunk_cv(data, chunk_index, is_root);
+        *cursor = end;
+        Some(cv)
+    } else {
+        let left_chunks = left_subtree_chunks(num_chunks);
+        let left_len = left_chunks * CHUNK_LEN;
+        let left_overlaps = offset < range_end && range_start < offset + left_len;
+        let right_overlaps = range_start < offset + data_len && offset + left_len < range_end;
+
+        let parent_end = cursor.checked_add(64)?;
+        if parent_end > ctx.input.len() {
+            return None;
+        }
+        let parent_left: [u8; 32] = ctx.input[*cursor..*cursor + 32].try_into().ok()?;
+        let parent_right: [u8; 32] = ctx.input[*cursor + 32..parent_end].try_into().ok()?;
+        *cursor = parent_end;
+
+        let left_cv = if left_overlaps {
+            decode_subtree(ctx, cursor, offset, left_len, chunk_index, false, range_start, range_end)?
+        } else {
+            parent_left
+        };
+        let right_cv = if right_overlaps {
+            decode_subtree(ctx, cursor, offset + left_len, data_len - left_len, chunk_index + left_chunks as u64, false, range_start, range_end)?
+        } else {
+            parent_right
+        };
+
+        if left_cv != parent_left || right_cv != parent_right {
+            return None;
+        }
+        Some(compute_parent_cv(&parent_left, &parent_right, is_root))
+    }
+}
+
+/// Verify a Bao slice encoding against a trusted root.
+/// `root_ptr` points to the 32 trusted root bytes; the session's input buffer holds the
+/// slice encoding (8-byte length header + the reduced tree) covering `[start, start+len)`.
+/// Recomputes every CV the slice carries and rejects the slice unless the derived
+/// root matches. Returns 1 if the slice is valid, 0 on any CV mismatch, malformed length
+/// header, or truncated/out-of-bounds encoding - the input buffer's own length header is
+/// untrusted, so it's checked against the buffer's real capacity before it ever drives
+/// `cursor` arithmetic. Plain (unkeyed) hashing only, see the module doc.
+#[wasm_bindgen]
+pub fn verify_slice(ctx: *mut Ctx, root_ptr: *const u8, start: usize, len: usize) -> u32 {
+    unsafe {
+        let ctx = &*ctx;
+        let root: [u8; 32] = core::slice::from_raw_parts(root_ptr, 32).try_into().unwrap();
+        let content_len = u64::from_le_bytes(ctx.input[..8].try_into().unwrap()) as usize;
+        if content_len > ctx.input.len() {
+            return 0;
+        }
+        let Some(range_end) = start.checked_add(len) else {
+            return 0;
+        };
+        let mut cursor = 8;
+        match decode_subtree(ctx, &mut cursor, 0, content_len, 0, true, start, range_end) {
+            Some(cv) if cv == root => 1,
+            _ => 0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -206,4 +587,198 @@ mod tests {
         // d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24
         assert_eq!(cv[0], 0xd7);
     }
+
+    #[test]
+    fn test_batch_chunk_cvs_matches_scalar() {
+        let ctx = create_context();
+        unsafe {
+            let data = [0x42u8; CHUNK_LEN * 3];
+            (*ctx).input[..data.len()].copy_from_slice(&data);
+            batch_chunk_cvs(ctx, 3, 0);
+
+            for i in 0..3 {
+                let chunk = &data[i * CHUNK_LEN..(i + 1) * CHUNK_LEN];
+                let expected = compute_chunk_cv(chunk, i as u64, false);
+                let actual = &(*ctx).output[i * OUT_LEN..(i + 1) * OUT_LEN];
+                assert_eq!(actual, &expected[..]);
+            }
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_bao_encode_verify_slice_round_trip() {
+        let ctx = create_context();
+        unsafe {
+            let content_len = CHUNK_LEN * 3 + 17;
+            for (i, byte) in (*ctx).input[..content_len].iter_mut().enumerate() {
+                *byte = (i % 256) as u8;
+            }
+            let written = bao_encode(ctx, content_len);
+            assert!(written > 0);
+            let root_cv = hash_subtree_cv(&*ctx, 0, content_len, 0, true);
+            let encoding = (*ctx).output[..written].to_vec();
+
+            (*ctx).input[..written].copy_from_slice(&encoding);
+            assert_eq!(verify_slice(ctx, root_cv.as_ptr(), 0, content_len), 1);
+
+            // Flip a content byte and confirm the tampered slice is rejected.
+            let mut tampered = encoding.clone();
+            let last = tampered.len() - 1;
+            tampered[last] ^= 0xff;
+            (*ctx).input[..written].copy_from_slice(&tampered);
+            assert_eq!(verify_slice(ctx, root_cv.as_ptr(), 0, content_len), 0);
+
+            // A bogus, oversized length header must be rejected rather than panic.
+            (*ctx).input[..written].copy_from_slice(&encoding);
+            let bogus_len = (INPUT_SIZE as u64) + 1;
+            (*ctx).input[..8].copy_from_slice(&bogus_len.to_le_bytes());
+            assert_eq!(verify_slice(ctx, root_cv.as_ptr(), 0, content_len), 0);
+
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_bao_encode_rejects_output_overflow() {
+        let ctx = create_context();
+        unsafe {
+            // The full input buffer's worth of chunks needs more than OUTPUT_SIZE bytes
+            // once the parent nodes and length header are accounted for.
+            assert_eq!(bao_encode(ctx, INPUT_SIZE), 0);
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_keyed_and_derive_key_diverge_from_plain() {
+        let ctx = create_context();
+        unsafe {
+            let data = b"some input data";
+            (*ctx).input[..data.len()].copy_from_slice(data);
+            hash_buffer(ctx, data.len(), MODE_PLAIN);
+            let plain_cv = (*ctx).output[..OUT_LEN].to_vec();
+
+            let key = [0x11u8; 32];
+            (*ctx).input[..32].copy_from_slice(&key);
+            set_key(ctx);
+            (*ctx).input[..data.len()].copy_from_slice(data);
+            hash_buffer(ctx, data.len(), MODE_KEYED_HASH);
+            let keyed_cv = (*ctx).output[..OUT_LEN].to_vec();
+            assert_ne!(plain_cv, keyed_cv);
+
+            let context = b"example context string";
+            assert_eq!(derive_key(ctx, context.as_ptr(), context.len()), 1);
+            (*ctx).input[..data.len()].copy_from_slice(data);
+            hash_buffer(ctx, data.len(), MODE_DERIVE_KEY_MATERIAL);
+            let derived_cv = (*ctx).output[..OUT_LEN].to_vec();
+            assert_ne!(plain_cv, derived_cv);
+            assert_ne!(keyed_cv, derived_cv);
+
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_derive_key_handles_long_context_and_rejects_invalid_utf8() {
+        let ctx = create_context();
+        unsafe {
+            // BLAKE3 contexts are `&str`, handled internally by `Hasher::new_derive_key`
+            // regardless of length, so a multi-chunk-long context must still succeed.
+            let long_context = vec![b'a'; CHUNK_LEN * 2 + 100];
+            assert_eq!(derive_key(ctx, long_context.as_ptr(), long_context.len()), 1);
+
+            let invalid_utf8 = [0xffu8, 0xfe];
+            assert_eq!(derive_key(ctx, invalid_utf8.as_ptr(), invalid_utf8.len()), 0);
+
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_finalize_xof_seek_continuation() {
+        let ctx = create_context();
+        unsafe {
+            let data = b"xof test data";
+            (*ctx).input[..data.len()].copy_from_slice(data);
+            assert_eq!(set_xof_input(ctx, data.len(), MODE_PLAIN), 1);
+
+            let full_len = finalize_xof(ctx, 128, 0);
+            assert_eq!(full_len, 128);
+            let full = (*ctx).output[..128].to_vec();
+
+            let first_len = finalize_xof(ctx, 64, 0);
+            assert_eq!(first_len, 64);
+            let first = (*ctx).output[..64].to_vec();
+
+            let second_len = finalize_xof(ctx, 64, 64);
+            assert_eq!(second_len, 64);
+            let second = (*ctx).output[..64].to_vec();
+
+            assert_eq!(&full[..64], &first[..]);
+            assert_eq!(&full[64..], &second[..]);
+
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_contexts_are_independent_sessions() {
+        let ctx_a = create_context();
+        let ctx_b = create_context();
+        unsafe {
+            let data_a = b"session a data";
+            let data_b = b"session b data";
+            (*ctx_a).input[..data_a.len()].copy_from_slice(data_a);
+            (*ctx_b).input[..data_b.len()].copy_from_slice(data_b);
+
+            chunk_cv(ctx_a, data_a.len(), 0, true);
+            chunk_cv(ctx_b, data_b.len(), 0, true);
+
+            let cv_a = (*ctx_a).output[..OUT_LEN].to_vec();
+            let cv_b = (*ctx_b).output[..OUT_LEN].to_vec();
+            assert_ne!(cv_a, cv_b);
+
+            let expected_a = compute_chunk_cv(data_a, 0, true);
+            assert_eq!(cv_a, expected_a);
+
+            destroy_context(ctx_a);
+            destroy_context(ctx_b);
+        }
+    }
+
+    #[test]
+    fn test_hash_subtree_matches_build_tree_single_pass() {
+        let ctx = create_context();
+        unsafe {
+            let num_chunks = 5;
+            let content_len = num_chunks * CHUNK_LEN;
+            for (i, byte) in (*ctx).input[..content_len].iter_mut().enumerate() {
+                *byte = (i % 256) as u8;
+            }
+
+            let direct_len = hash_subtree(ctx, content_len, 0, true);
+            assert_eq!(direct_len, 32);
+            let direct_cv = (*ctx).output[..32].to_vec();
+
+            batch_chunk_cvs(ctx, num_chunks, 0);
+            let leaf_cvs = (*ctx).output[..num_chunks * OUT_LEN].to_vec();
+            (*ctx).input[..num_chunks * OUT_LEN].copy_from_slice(&leaf_cvs);
+            let tree_len = build_tree_single_pass(ctx, num_chunks);
+            assert_eq!(tree_len, 32);
+            let tree_cv = (*ctx).output[..32].to_vec();
+
+            assert_eq!(direct_cv, tree_cv);
+            destroy_context(ctx);
+        }
+    }
+
+    #[test]
+    fn test_hash_subtree_rejects_oversized_input() {
+        let ctx = create_context();
+        unsafe {
+            assert_eq!(hash_subtree(ctx, INPUT_SIZE + 1, 0, true), 0);
+            destroy_context(ctx);
+        }
+    }
 }